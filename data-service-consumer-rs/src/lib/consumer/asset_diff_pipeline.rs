@@ -0,0 +1,231 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::consumer::models::assets::{AssetOverride, AssetUpdate};
+
+/// Depth of each stage of the pipeline, for metrics and for deciding
+/// whether to grow or shrink the worker pool.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct QueueInfo {
+    pub unprocessed_size: usize,
+    pub processing_size: usize,
+    pub ready_size: usize,
+}
+
+/// Computes each asset's `superseded_by` diff on a pool of worker
+/// threads. Updates are partitioned by `asset_id` (same hash used by
+/// `AssetUpdate`'s `Hash`/`Eq` impls) so every update for one asset stays
+/// on a single worker and is processed in order, while unrelated assets
+/// diff concurrently. Sized once at construction, since resizing the
+/// shard count would scatter a single asset's updates across workers and
+/// break that ordering guarantee; `recommended_worker_count` lets a
+/// caller recreate the pipeline with a new size between batches instead.
+pub struct AssetDiffPipeline {
+    shards: Vec<mpsc::Sender<AssetUpdate>>,
+    ready_rx: mpsc::Receiver<AssetOverride>,
+    unprocessed: Arc<AtomicUsize>,
+    processing: Arc<AtomicUsize>,
+    ready: Arc<AtomicUsize>,
+}
+
+impl AssetDiffPipeline {
+    pub fn new(worker_count: usize) -> Self {
+        let unprocessed = Arc::new(AtomicUsize::new(0));
+        let processing = Arc::new(AtomicUsize::new(0));
+        let ready = Arc::new(AtomicUsize::new(0));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let shards = (0..worker_count.max(1))
+            .map(|_| {
+                let (tx, rx) = mpsc::channel::<AssetUpdate>();
+                let ready_tx = ready_tx.clone();
+                let unprocessed = Arc::clone(&unprocessed);
+                let processing = Arc::clone(&processing);
+                let ready = Arc::clone(&ready);
+
+                thread::spawn(move || {
+                    // Last uid seen for each asset_id this worker owns, so
+                    // the diff it emits reflects the actual update right
+                    // before this one instead of a placeholder echo.
+                    // Sound because `shard_for` pins every update for one
+                    // asset_id to this same worker in submission order.
+                    let mut prior_uid: HashMap<String, i64> = HashMap::new();
+
+                    while let Ok(update) = rx.recv() {
+                        unprocessed.fetch_sub(1, Ordering::SeqCst);
+                        processing.fetch_add(1, Ordering::SeqCst);
+
+                        let diff = compute_diff(&mut prior_uid, &update);
+
+                        processing.fetch_sub(1, Ordering::SeqCst);
+                        if let Some(diff) = diff {
+                            ready.fetch_add(1, Ordering::SeqCst);
+                            if ready_tx.send(diff).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                });
+
+                tx
+            })
+            .collect();
+
+        AssetDiffPipeline {
+            shards,
+            ready_rx,
+            unprocessed,
+            processing,
+            ready,
+        }
+    }
+
+    /// Routes `update` to the worker owning its `asset_id`.
+    pub fn submit(&self, update: AssetUpdate) {
+        let shard = shard_for(&update.asset_id, self.shards.len());
+        self.unprocessed.fetch_add(1, Ordering::SeqCst);
+        self.shards[shard]
+            .send(update)
+            .expect("asset-diff worker shard is gone");
+    }
+
+    /// Drains one verified diff, if any are ready yet.
+    pub fn try_recv_ready(&self) -> Option<AssetOverride> {
+        let item = self.ready_rx.try_recv().ok();
+        if item.is_some() {
+            self.ready.fetch_sub(1, Ordering::SeqCst);
+        }
+        item
+    }
+
+    pub fn queue_info(&self) -> QueueInfo {
+        QueueInfo {
+            unprocessed_size: self.unprocessed.load(Ordering::SeqCst),
+            processing_size: self.processing.load(Ordering::SeqCst),
+            ready_size: self.ready.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Suggests a worker count for the *next* pipeline instance from the
+/// current backlog: grow while unprocessed work is piling up relative to
+/// what's draining, shrink back down once it's caught up, so the
+/// pipeline tracks the rate of the slowest stage instead of running a
+/// fixed-size pool sized for the worst case.
+pub fn recommended_worker_count(info: QueueInfo, current_worker_count: usize) -> usize {
+    let draining = info.processing_size + info.ready_size;
+    let ratio = if draining == 0 {
+        if info.unprocessed_size == 0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        info.unprocessed_size as f64 / draining as f64
+    };
+
+    if ratio > 2.0 {
+        (current_worker_count * 2).max(1)
+    } else if ratio < 0.5 && current_worker_count > 1 {
+        (current_worker_count / 2).max(1)
+    } else {
+        current_worker_count.max(1)
+    }
+}
+
+fn shard_for(asset_id: &str, shard_count: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    asset_id.hash(&mut hasher);
+    (hasher.finish() as usize) % shard_count.max(1)
+}
+
+/// Diffs `update` against the last update this worker saw for the same
+/// `asset_id`: an asset's first update in this pipeline's lifetime is an
+/// issuance with nothing to supersede yet, so it produces no override;
+/// every later update closes out the one before it.
+fn compute_diff(prior_uid: &mut HashMap<String, i64>, update: &AssetUpdate) -> Option<AssetOverride> {
+    let had_prior = prior_uid
+        .insert(update.asset_id.clone(), update.uid)
+        .is_some();
+
+    had_prior.then(|| AssetOverride {
+        superseded_by: update.uid,
+        id: update.asset_id.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(asset_id: &str, uid: i64) -> AssetUpdate {
+        AssetUpdate {
+            block_uid: 1,
+            uid,
+            superseded_by: std::i64::MAX - 1,
+            asset_id: asset_id.to_string(),
+            decimals: 8,
+            name: "Asset".to_string(),
+            description: String::new(),
+            reissuable: false,
+            volume: 0,
+            script: None,
+            sponsorship: None,
+            nft: false,
+        }
+    }
+
+    #[test]
+    fn shard_for_is_deterministic_for_the_same_asset_id() {
+        assert_eq!(shard_for("asset-1", 8), shard_for("asset-1", 8));
+    }
+
+    #[test]
+    fn shard_for_spreads_distinct_asset_ids_across_shards() {
+        let shards: std::collections::HashSet<usize> = (0..64)
+            .map(|i| shard_for(&format!("asset-{}", i), 8))
+            .collect();
+        assert!(shards.len() > 1);
+    }
+
+    #[test]
+    fn shard_for_never_exceeds_shard_count() {
+        for i in 0..64 {
+            assert!(shard_for(&format!("asset-{}", i), 4) < 4);
+        }
+    }
+
+    #[test]
+    fn first_update_for_an_asset_has_nothing_to_supersede() {
+        let mut prior_uid = HashMap::new();
+        let diff = compute_diff(&mut prior_uid, &update("asset-1", 10));
+        assert!(diff.is_none());
+    }
+
+    #[test]
+    fn second_update_closes_out_the_first() {
+        let mut prior_uid = HashMap::new();
+        compute_diff(&mut prior_uid, &update("asset-1", 10));
+        let diff = compute_diff(&mut prior_uid, &update("asset-1", 20));
+
+        let diff = diff.expect("second update must supersede the first");
+        assert_eq!(diff.id, "asset-1");
+        assert_eq!(diff.superseded_by, 20);
+    }
+
+    #[test]
+    fn unrelated_assets_dont_interfere_with_each_others_diff() {
+        let mut prior_uid = HashMap::new();
+        assert!(compute_diff(&mut prior_uid, &update("asset-1", 10)).is_none());
+        assert!(compute_diff(&mut prior_uid, &update("asset-2", 11)).is_none());
+
+        let diff = compute_diff(&mut prior_uid, &update("asset-1", 30))
+            .expect("asset-1's second update must supersede its first");
+        assert_eq!(diff.id, "asset-1");
+        assert_eq!(diff.superseded_by, 30);
+    }
+}
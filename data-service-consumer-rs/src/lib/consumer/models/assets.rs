@@ -2,12 +2,13 @@ use crate::schema::*;
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable};
 use diesel_full_text_search::TsVector;
+use serde::Serialize;
 use std::hash::{Hash, Hasher};
 
 pub type BlockUid = i64;
 pub type UpdateUid = i64;
 
-#[derive(Clone, Debug, Insertable, Queryable)]
+#[derive(Clone, Debug, Serialize, Insertable, Queryable)]
 pub struct AssetUpdate {
     pub block_uid: i64,
     pub uid: i64,
@@ -37,13 +38,13 @@ impl Hash for AssetUpdate {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct AssetOverride {
     pub superseded_by: i64,
     pub id: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct DeletedAsset {
     pub uid: i64,
     pub id: String,
@@ -63,7 +64,7 @@ impl Hash for DeletedAsset {
     }
 }
 
-#[derive(Clone, Debug, Insertable, Queryable)]
+#[derive(Clone, Debug, Serialize, Insertable, Queryable)]
 pub struct AssetOrigin {
     pub asset_id: String,
     pub first_asset_update_uid: i64,
@@ -88,4 +89,93 @@ struct AssetsNames {
     asset_id: String,
     asset_name: Option<String>,
     searchable_asset_name: TsVector,
+}
+
+/// Checks the invariant `as_of`/`rollback_assets_to` rely on for a single
+/// asset's updates: their `[uid, superseded_by)` intervals form a
+/// contiguous, non-overlapping chain with exactly one live entry
+/// (`superseded_by == live_sentinel`). `updates` need not be pre-sorted.
+/// An empty slice is vacuously contiguous.
+pub fn superseded_by_chain_is_contiguous(updates: &[AssetUpdate], live_sentinel: i64) -> bool {
+    if updates.is_empty() {
+        return true;
+    }
+
+    let mut sorted: Vec<&AssetUpdate> = updates.iter().collect();
+    sorted.sort_by_key(|u| u.uid);
+
+    if sorted.iter().any(|u| u.superseded_by != live_sentinel && u.superseded_by <= u.uid) {
+        return false;
+    }
+
+    let chain_is_contiguous = sorted
+        .windows(2)
+        .all(|pair| pair[0].superseded_by == pair[1].uid);
+
+    let live_count = sorted
+        .iter()
+        .filter(|u| u.superseded_by == live_sentinel)
+        .count();
+
+    chain_is_contiguous && live_count == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIVE: i64 = std::i64::MAX - 1;
+
+    fn update(uid: i64, superseded_by: i64) -> AssetUpdate {
+        AssetUpdate {
+            block_uid: 1,
+            uid,
+            superseded_by,
+            asset_id: "asset".to_string(),
+            decimals: 8,
+            name: "Asset".to_string(),
+            description: String::new(),
+            reissuable: false,
+            volume: 0,
+            script: None,
+            sponsorship: None,
+            nft: false,
+        }
+    }
+
+    #[test]
+    fn empty_chain_is_contiguous() {
+        assert!(superseded_by_chain_is_contiguous(&[], LIVE));
+    }
+
+    #[test]
+    fn single_live_update_is_contiguous() {
+        let updates = vec![update(1, LIVE)];
+        assert!(superseded_by_chain_is_contiguous(&updates, LIVE));
+    }
+
+    #[test]
+    fn unsorted_contiguous_chain_is_valid() {
+        let updates = vec![update(3, LIVE), update(1, 2), update(2, 3)];
+        assert!(superseded_by_chain_is_contiguous(&updates, LIVE));
+    }
+
+    #[test]
+    fn gap_in_chain_is_rejected() {
+        // uid 1 is superseded by an update that was never inserted.
+        let updates = vec![update(1, 5), update(2, LIVE)];
+        assert!(!superseded_by_chain_is_contiguous(&updates, LIVE));
+    }
+
+    #[test]
+    fn two_live_entries_are_rejected() {
+        let updates = vec![update(1, LIVE), update(2, LIVE)];
+        assert!(!superseded_by_chain_is_contiguous(&updates, LIVE));
+    }
+
+    #[test]
+    fn no_live_entry_is_rejected() {
+        let updates = vec![update(1, 2)];
+        assert!(!superseded_by_chain_is_contiguous(&updates, LIVE));
+    }
 }
\ No newline at end of file
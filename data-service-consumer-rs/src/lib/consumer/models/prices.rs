@@ -0,0 +1,14 @@
+use crate::schema::*;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+
+#[derive(Clone, Debug, Insertable, Queryable)]
+#[table_name = "prices"]
+pub struct PriceQuote {
+    pub asset_id: String,
+    pub source: String,
+    pub height: i32,
+    pub timestamp: NaiveDateTime,
+    pub quote: BigDecimal,
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use futures::stream::{FuturesOrdered, StreamExt};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Tunables for the fetch-ahead ingestion pipeline. Defaults are
+/// conservative; callers tune these from config to match the node's RPC
+/// capacity and Postgres's write throughput.
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineConfig {
+    /// Max number of block fetches kept in flight at once.
+    pub fetch_concurrency: usize,
+    /// Capacity of the channel between the fetch stage and the writer;
+    /// bounds memory and provides backpressure when the DB is slow.
+    pub channel_buffer: usize,
+    /// How often to log a blocks/sec throughput line.
+    pub report_interval: Duration,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            fetch_concurrency: 8,
+            channel_buffer: 32,
+            report_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Runs `fetch` over `heights` with up to `config.fetch_concurrency`
+/// requests in flight, preserving height order on the way out (FK-parent
+/// rows like txs must land before their args/payments), and feeds the
+/// ordered results through a bounded channel into `write`. The channel
+/// capacity is the backpressure: if `write` falls behind, the fetch
+/// stage blocks instead of growing memory without bound.
+pub async fn run_fetch_ahead<H, F, Fut, B, W>(
+    heights: H,
+    fetch: F,
+    mut write: W,
+    config: PipelineConfig,
+) -> Result<()>
+where
+    H: IntoIterator<Item = u32>,
+    H::IntoIter: Send + 'static,
+    F: Fn(u32) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<B>> + Send + 'static,
+    B: Send + 'static,
+    W: FnMut(B) -> Result<()>,
+{
+    let (tx, mut rx) = mpsc::channel::<Result<B>>(config.channel_buffer);
+    let mut heights = heights.into_iter();
+
+    let fetcher = {
+        let fetch = fetch.clone();
+        async move {
+            let mut in_flight = FuturesOrdered::new();
+
+            for height in heights.by_ref().take(config.fetch_concurrency) {
+                let fetch = fetch.clone();
+                in_flight.push_back(async move { fetch(height).await });
+            }
+
+            while let Some(result) = in_flight.next().await {
+                if tx.send(result).await.is_err() {
+                    // Writer side gone; stop fetching ahead.
+                    return;
+                }
+                if let Some(height) = heights.next() {
+                    let fetch = fetch.clone();
+                    in_flight.push_back(async move { fetch(height).await });
+                }
+            }
+        }
+    };
+
+    let fetcher_handle = tokio::spawn(fetcher);
+
+    let mut processed = 0u64;
+    let mut window_start = Instant::now();
+    let mut window_count = 0u64;
+
+    while let Some(block) = rx.recv().await {
+        write(block?)?;
+        processed += 1;
+        window_count += 1;
+
+        if window_start.elapsed() >= config.report_interval {
+            let rate = window_count as f64 / window_start.elapsed().as_secs_f64();
+            log::info!(
+                "ingestion throughput: {:.1} blocks/sec ({} total)",
+                rate,
+                processed
+            );
+            window_start = Instant::now();
+            window_count = 0;
+        }
+    }
+
+    fetcher_handle.await.ok();
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use anyhow::{Error, Result};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::Table;
+
+use crate::consumer::models::assets::{AssetOrigin, AssetOverride, AssetUpdate, DeletedAsset};
+use crate::db::PgAsyncPool;
+use crate::error::Error as AppError;
+use crate::schema::*;
+use crate::tuple_len::TupleLen;
+
+const PG_MAX_INSERT_FIELDS_COUNT: usize = 65535;
+
+/// Asset-update persistence over the same `deadpool-diesel` pool as
+/// `PgRepo`/`OffChainRepo`: synchronous Diesel 1.4 queries run inside
+/// `.interact()` on the pool's blocking thread pool, so a sync process
+/// can drive many batches concurrently over the pool without dedicating
+/// one thread per write.
+#[derive(Clone)]
+pub struct AssetRepo {
+    pool: PgAsyncPool,
+}
+
+pub fn new(pool: PgAsyncPool) -> AssetRepo {
+    AssetRepo { pool }
+}
+
+pub struct AssetRepoConnection<'c> {
+    conn: &'c PgConnection,
+}
+
+impl AssetRepo {
+    async fn transaction<F, R>(&self, f: F) -> Result<R>
+    where
+        F: for<'conn> FnOnce(&AssetRepoConnection<'conn>) -> Result<R>,
+        F: Send + 'static,
+        R: Send + 'static,
+    {
+        let connection = self.pool.get().await?;
+        connection
+            .interact(|conn| {
+                let ops = AssetRepoConnection { conn };
+                ops.conn.transaction(|| f(&ops))
+            })
+            .await
+            .expect("deadpool interaction failed")
+    }
+
+    pub async fn insert_asset_updates(&self, updates: Vec<AssetUpdate>) -> Result<()> {
+        self.transaction(move |ops| ops.insert_asset_updates(&updates))
+            .await
+    }
+
+    pub async fn set_overrides(&self, overrides: Vec<AssetOverride>) -> Result<()> {
+        self.transaction(move |ops| ops.set_overrides(&overrides))
+            .await
+    }
+
+    pub async fn delete_assets(&self, deleted: Vec<DeletedAsset>) -> Result<()> {
+        self.transaction(move |ops| ops.delete_assets(&deleted))
+            .await
+    }
+
+    #[allow(dead_code)]
+    pub async fn insert_asset_origins(&self, origins: Vec<AssetOrigin>) -> Result<()> {
+        self.transaction(move |ops| ops.insert_asset_origins(&origins))
+            .await
+    }
+}
+
+impl AssetRepoConnection<'_> {
+    fn insert_asset_updates(&self, updates: &[AssetUpdate]) -> Result<()> {
+        for chunk in updates.chunks(chunk_size::<asset_updates::table>()) {
+            diesel::insert_into(asset_updates::table)
+                .values(chunk)
+                .on_conflict((asset_updates::superseded_by, asset_updates::asset_id))
+                .do_nothing()
+                .execute(self.conn)
+                .map_err(|err| {
+                    let context = format!("Cannot insert asset updates: {}", err);
+                    Error::new(AppError::DbDieselError(err)).context(context)
+                })?;
+        }
+        Ok(())
+    }
+
+    fn set_overrides(&self, overrides: &[AssetOverride]) -> Result<()> {
+        for over in overrides {
+            diesel::sql_query(
+                "UPDATE asset_updates SET superseded_by = $1 WHERE asset_id = $2 AND superseded_by = $3",
+            )
+            .bind::<diesel::sql_types::BigInt, _>(over.superseded_by)
+            .bind::<diesel::sql_types::VarChar, _>(&over.id)
+            .bind::<diesel::sql_types::BigInt, _>(std::i64::MAX - 1)
+            .execute(self.conn)
+            .map_err(|err| {
+                let context = format!("Cannot set asset overrides: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn delete_assets(&self, deleted: &[DeletedAsset]) -> Result<()> {
+        let uids: Vec<i64> = deleted.iter().map(|d| d.uid).collect();
+        diesel::delete(asset_updates::table)
+            .filter(asset_updates::uid.eq_any(&uids))
+            .execute(self.conn)
+            .map(|_| ())
+            .map_err(|err| {
+                let context = format!("Cannot delete assets: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })
+    }
+
+    fn insert_asset_origins(&self, origins: &[AssetOrigin]) -> Result<()> {
+        for chunk in origins.chunks(chunk_size::<asset_origins::table>()) {
+            diesel::insert_into(asset_origins::table)
+                .values(chunk)
+                .on_conflict(asset_origins::asset_id)
+                .do_nothing()
+                .execute(self.conn)
+                .map_err(|err| {
+                    let context = format!("Cannot insert asset origins: {}", err);
+                    Error::new(AppError::DbDieselError(err)).context(context)
+                })?;
+        }
+        Ok(())
+    }
+}
+
+// Same bind-parameter-limit math as the sync `chunked` helper in pg.rs,
+// computed per-table at the call site since it's only needed here.
+fn chunk_size<Tab: Table>() -> usize
+where
+    Tab::AllColumns: TupleLen,
+{
+    (PG_MAX_INSERT_FIELDS_COUNT / Tab::all_columns().len()) / 10 * 10
+}
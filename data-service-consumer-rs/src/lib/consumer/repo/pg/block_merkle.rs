@@ -0,0 +1,83 @@
+use sha2::{Digest, Sha256};
+
+/// Root stored for a block with zero transactions, so an empty block
+/// still has a well-defined, comparable root.
+pub const EMPTY_BLOCK_ROOT: [u8; 32] = [0u8; 32];
+
+fn hash_leaf(tx_id: &[u8]) -> Vec<u8> {
+    Sha256::digest(tx_id).to_vec()
+}
+
+fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// Reduces a block's ordered transaction ids to a single Merkle root:
+/// hash each id as a leaf, then repeatedly combine adjacent pairs
+/// (duplicating the last node when a level has an odd count) until one
+/// hash remains. Pure/DB-free so it can be used both when persisting a
+/// block and when re-verifying one already in the database.
+pub fn compute_block_root(tx_ids: &[Vec<u8>]) -> Vec<u8> {
+    if tx_ids.is_empty() {
+        return EMPTY_BLOCK_ROOT.to_vec();
+    }
+
+    let mut level: Vec<Vec<u8>> = tx_ids.iter().map(|id| hash_leaf(id)).collect();
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(level.last().expect("checked non-empty above").clone());
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    level.remove(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_block_uses_sentinel_root() {
+        assert_eq!(compute_block_root(&[]), EMPTY_BLOCK_ROOT.to_vec());
+    }
+
+    #[test]
+    fn single_tx_block_root_is_its_leaf_hash() {
+        let tx_id = b"tx-1".to_vec();
+        assert_eq!(compute_block_root(&[tx_id.clone()]), hash_leaf(&tx_id));
+    }
+
+    #[test]
+    fn even_tx_count_pairs_without_duplication() {
+        let a = b"tx-a".to_vec();
+        let b = b"tx-b".to_vec();
+        let expected = hash_pair(&hash_leaf(&a), &hash_leaf(&b));
+        assert_eq!(compute_block_root(&[a, b]), expected);
+    }
+
+    #[test]
+    fn odd_tx_count_duplicates_last_leaf() {
+        let a = b"tx-a".to_vec();
+        let b = b"tx-b".to_vec();
+        let c = b"tx-c".to_vec();
+        let (ha, hb, hc) = (hash_leaf(&a), hash_leaf(&b), hash_leaf(&c));
+        let expected = hash_pair(&hash_pair(&ha, &hb), &hash_pair(&hc, &hc));
+        assert_eq!(compute_block_root(&[a, b, c]), expected);
+    }
+
+    #[test]
+    fn root_is_sensitive_to_tx_order() {
+        let a = b"tx-a".to_vec();
+        let b = b"tx-b".to_vec();
+        assert_ne!(
+            compute_block_root(&[a.clone(), b.clone()]),
+            compute_block_root(&[b, a])
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use diesel::result::Error as DslError;
+use diesel::Table;
+
+use crate::tuple_len::TupleLen;
+
+use super::PG_MAX_INSERT_FIELDS_COUNT;
+
+/// At or above this many pending rows in a single flush, `insert_txs_16` may
+/// switch to `chunked_with_size`'s batches (still capped by
+/// `bulk_chunk_size`, just without `chunked`'s extra round-to-10 margin)
+/// during an initial full-history backfill, trading a little per-statement
+/// planning cost for fewer round trips. Gated behind
+/// `PgRepo::backfill_transaction` (see `PgRepoOperations::backfill`) rather
+/// than firing on size alone: this crate's idempotent re-sync story
+/// depends on every insert being a `do_nothing` upsert, so a batch that
+/// overlaps already-synced rows must still go through that upsert instead
+/// of risking a primary-key violation on the hot tip-following path.
+pub const BULK_LOAD_THRESHOLD: usize = 50_000;
+
+/// Largest chunk size that keeps `T`'s columns under
+/// `PG_MAX_INSERT_FIELDS_COUNT` bound parameters per statement — the same
+/// limit `chunked` in pg.rs guards against, without its extra round-down
+/// to a multiple of 10, since bulk loading cares about fewer round trips
+/// more than a tidy chunk size.
+pub fn bulk_chunk_size<T>() -> usize
+where
+    T: Table,
+    T::AllColumns: TupleLen,
+{
+    PG_MAX_INSERT_FIELDS_COUNT / T::all_columns().len()
+}
+
+/// Same shape as `chunked` in pg.rs, but with an explicit chunk size
+/// instead of computing one inline, so the caller can pass
+/// `bulk_chunk_size::<table>()`.
+pub fn chunked_with_size<V, F, R>(
+    values: &[V],
+    chunk_size: usize,
+    mut query_fn: F,
+) -> Result<Vec<R>, DslError>
+where
+    F: FnMut(&[V]) -> Result<R, DslError>,
+{
+    values
+        .chunks(chunk_size.max(1))
+        .map(&mut query_fn)
+        .collect()
+}
@@ -0,0 +1,173 @@
+use anyhow::{Error, Result};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+use crate::error::Error as AppError;
+use crate::schema::*;
+
+/// A block moved off the canonical chain by a reorg, kept around (instead
+/// of hard-deleted) so its transactions can be replayed if they are still
+/// valid on the winning branch.
+#[derive(Clone, Debug, Queryable, Insertable)]
+#[table_name = "orphaned_blocks"]
+pub struct OrphanedBlock {
+    pub id: String,
+    pub height: i32,
+}
+
+#[derive(Clone, Debug, Queryable, Insertable)]
+#[table_name = "orphaned_txs"]
+pub struct OrphanedTx {
+    pub block_id: String,
+    pub tx_id: String,
+}
+
+/// Copies every block (and its transactions) above `block_uid` from the
+/// canonical tables into the orphan side tables, so they survive the
+/// caller's delete of the canonical rows. Must run before those rows are
+/// deleted, in the same transaction.
+pub fn archive_above(conn: &PgConnection, block_uid: &i64) -> Result<()> {
+    let blocks: Vec<(String, i32)> = blocks_microblocks::table
+        .select((blocks_microblocks::id, blocks_microblocks::height))
+        .filter(blocks_microblocks::uid.gt(block_uid))
+        .get_results(conn)
+        .map_err(|err| {
+            let context = format!("Cannot select blocks to archive: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+
+    if blocks.is_empty() {
+        return Ok(());
+    }
+
+    let orphaned_blocks = blocks
+        .iter()
+        .map(|(id, height)| OrphanedBlock {
+            id: id.clone(),
+            height: *height,
+        })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(orphaned_blocks::table)
+        .values(&orphaned_blocks)
+        .on_conflict(orphaned_blocks::id)
+        .do_nothing()
+        .execute(conn)
+        .map_err(|err| {
+            let context = format!("Cannot insert orphaned blocks: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+
+    let txs: Vec<(String, String)> = txs::table
+        .inner_join(
+            blocks_microblocks::table.on(txs::block_uid.eq(blocks_microblocks::uid)),
+        )
+        .select((blocks_microblocks::id, txs::id))
+        .filter(blocks_microblocks::uid.gt(block_uid))
+        .get_results(conn)
+        .map_err(|err| {
+            let context = format!("Cannot select transactions to archive: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+
+    let orphaned_txs = txs
+        .into_iter()
+        .map(|(block_id, tx_id)| OrphanedTx { block_id, tx_id })
+        .collect::<Vec<_>>();
+
+    diesel::insert_into(orphaned_txs::table)
+        .values(&orphaned_txs)
+        .on_conflict((orphaned_txs::block_id, orphaned_txs::tx_id))
+        .do_nothing()
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| {
+            let context = format!("Cannot insert orphaned transactions: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
+
+/// Pops the current chain tip into the orphan pool and returns the ids of
+/// the transactions it carried that are not present on the remaining
+/// canonical chain, i.e. the ones still unapplied there.
+pub fn pop_block(conn: &PgConnection) -> Result<Option<(String, Vec<String>)>> {
+    let tip: Option<(i64, String, i32)> = blocks_microblocks::table
+        .select((
+            blocks_microblocks::uid,
+            blocks_microblocks::id,
+            blocks_microblocks::height,
+        ))
+        .order(blocks_microblocks::uid.desc())
+        .first(conn)
+        .optional()
+        .map_err(|err| {
+            let context = format!("Cannot find chain tip: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+
+    let Some((tip_uid, tip_id, _height)) = tip else {
+        return Ok(None);
+    };
+
+    archive_above(conn, &(tip_uid - 1))?;
+
+    diesel::delete(blocks_microblocks::table)
+        .filter(blocks_microblocks::uid.eq(tip_uid))
+        .execute(conn)
+        .map_err(|err| {
+            let context = format!("Cannot delete popped block: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+
+    // Only after the tip is gone: while it is still canonical, its own
+    // transactions would be filtered out as already applied.
+    let unapplied = take_unapplied_txs(conn, &tip_id)?;
+
+    Ok(Some((tip_id, unapplied)))
+}
+
+/// Returns the orphaned transaction ids for `block_id` that are not
+/// present anywhere on the current canonical chain, i.e. the ones a
+/// consumer should re-insert on the winning branch.
+pub fn take_unapplied_txs(conn: &PgConnection, block_id: &str) -> Result<Vec<String>> {
+    orphaned_txs::table
+        .select(orphaned_txs::tx_id)
+        .filter(orphaned_txs::block_id.eq(block_id))
+        .filter(
+            orphaned_txs::tx_id.ne_all(
+                txs::table.select(txs::id),
+            ),
+        )
+        .get_results(conn)
+        .map_err(|err| {
+            let context = format!("Cannot collect unapplied transactions: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
+
+/// True if `block_id` has already been seen, either on the canonical
+/// chain or on a since-abandoned fork, so the consumer can skip
+/// re-downloading it.
+pub fn is_known_block(conn: &PgConnection, block_id: &str) -> Result<bool> {
+    let on_canonical_chain: i64 = blocks_microblocks::table
+        .filter(blocks_microblocks::id.eq(block_id))
+        .count()
+        .get_result(conn)
+        .map_err(|err| {
+            let context = format!("Cannot check canonical blocks: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+    if on_canonical_chain > 0 {
+        return Ok(true);
+    }
+
+    let on_orphaned_chain: i64 = orphaned_blocks::table
+        .filter(orphaned_blocks::id.eq(block_id))
+        .count()
+        .get_result(conn)
+        .map_err(|err| {
+            let context = format!("Cannot check orphaned blocks: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+    Ok(on_orphaned_chain > 0)
+}
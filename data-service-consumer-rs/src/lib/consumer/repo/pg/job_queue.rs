@@ -0,0 +1,126 @@
+use anyhow::{Error, Result};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DslError;
+use diesel_derive_enum::DbEnum;
+use serde_json::Value as Json;
+use uuid::Uuid;
+
+use crate::error::Error as AppError;
+use crate::schema::*;
+
+/// Mirrors the Postgres `job_status` enum so a failed batch can be
+/// requeued and retried instead of crashing the sync loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DbEnum)]
+#[DieselType = "Job_status"]
+pub enum JobStatus {
+    New,
+    Running,
+    Failed,
+}
+
+#[derive(Clone, Debug, Queryable, Insertable)]
+#[table_name = "job_queue"]
+pub struct Job {
+    pub id: Uuid,
+    pub queue: String,
+    pub job: Json,
+    pub status: JobStatus,
+    pub heartbeat: NaiveDateTime,
+}
+
+/// Enqueues `job` under `queue` in status `New`, ready to be claimed.
+pub fn enqueue_job(conn: &PgConnection, queue: &str, job: Json) -> Result<Uuid> {
+    let id = Uuid::new_v4();
+    diesel::insert_into(job_queue::table)
+        .values(&Job {
+            id,
+            queue: queue.to_owned(),
+            job,
+            status: JobStatus::New,
+            heartbeat: Utc::now().naive_utc(),
+        })
+        .execute(conn)
+        .map_err(|err| {
+            let context = format!("Cannot enqueue job on queue {}: {}", queue, err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })?;
+    Ok(id)
+}
+
+/// Atomically claims the oldest `New` job on `queue`, marking it
+/// `Running`. `FOR UPDATE SKIP LOCKED` lets multiple workers poll the
+/// same queue without claiming the same job twice.
+pub fn claim_job(conn: &PgConnection, queue: &str) -> Result<Option<Job>> {
+    conn.transaction(|| {
+        let job = job_queue::table
+            .filter(job_queue::queue.eq(queue))
+            .filter(job_queue::status.eq(JobStatus::New))
+            .order(job_queue::heartbeat.asc())
+            .for_update()
+            .skip_locked()
+            .first::<Job>(conn)
+            .optional()?;
+
+        let Some(job) = job else { return Ok(None) };
+
+        diesel::update(job_queue::table)
+            .filter(job_queue::id.eq(job.id))
+            .set((
+                job_queue::status.eq(JobStatus::Running),
+                job_queue::heartbeat.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+
+        Ok(Some(job))
+    })
+    .map_err(|err: DslError| {
+        let context = format!("Cannot claim job on queue {}: {}", queue, err);
+        Error::new(AppError::DbDieselError(err)).context(context)
+    })
+}
+
+/// Refreshes the heartbeat of a still-running job so `requeue_stale`
+/// doesn't reclaim it out from under its worker.
+pub fn heartbeat_job(conn: &PgConnection, id: Uuid) -> Result<()> {
+    diesel::update(job_queue::table)
+        .filter(job_queue::id.eq(id))
+        .set(job_queue::heartbeat.eq(Utc::now().naive_utc()))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| {
+            let context = format!("Cannot heartbeat job {}: {}", id, err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
+
+/// Removes a successfully processed job from the queue.
+pub fn complete_job(conn: &PgConnection, id: Uuid) -> Result<()> {
+    diesel::delete(job_queue::table)
+        .filter(job_queue::id.eq(id))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| {
+            let context = format!("Cannot complete job {}: {}", id, err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
+
+/// Resets jobs whose heartbeat is older than `ttl` back to `New`, so a
+/// worker that died mid-job doesn't strand it in `Running` forever.
+pub fn requeue_stale(conn: &PgConnection, ttl: Duration) -> Result<usize> {
+    let cutoff = Utc::now().naive_utc() - ttl;
+    diesel::update(job_queue::table)
+        .filter(job_queue::status.eq(JobStatus::Running))
+        .filter(job_queue::heartbeat.lt(cutoff))
+        .set((
+            job_queue::status.eq(JobStatus::New),
+            job_queue::heartbeat.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+        .map_err(|err| {
+            let context = format!("Cannot requeue stale jobs: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+/// Running insert stats for one `txs_*`/asset table, aggregated across
+/// every flushed batch since process start.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TableStats {
+    pub rows_submitted: u64,
+    pub rows_inserted: u64,
+    pub batches: u64,
+    pub flush_time: Duration,
+}
+
+impl TableStats {
+    pub fn rows_skipped(&self) -> u64 {
+        self.rows_submitted.saturating_sub(self.rows_inserted)
+    }
+}
+
+static STATS: Lazy<Mutex<HashMap<&'static str, TableStats>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records one flushed batch: how many rows were submitted, how many
+/// Postgres actually inserted (the rest were `ON CONFLICT DO NOTHING`
+/// skips), and how long the flush took. Called once per table per insert
+/// call, with the counts summed over all of that call's chunks, so re-syncing already-present heights becomes visible as
+/// "rows submitted, nothing inserted" instead of silent, unmeasured work.
+pub fn record_insert(table: &'static str, rows_submitted: usize, rows_inserted: usize, elapsed: Duration) {
+    let mut stats = STATS.lock().expect("stats mutex poisoned");
+    let entry = stats.entry(table).or_default();
+    entry.rows_submitted += rows_submitted as u64;
+    entry.rows_inserted += rows_inserted as u64;
+    entry.batches += 1;
+    entry.flush_time += elapsed;
+}
+
+/// Snapshot of every table's stats so far, for a metrics/stats endpoint
+/// or a structured per-flush log line.
+pub fn snapshot() -> Vec<(&'static str, TableStats)> {
+    STATS
+        .lock()
+        .expect("stats mutex poisoned")
+        .iter()
+        .map(|(table, stats)| (*table, *stats))
+        .collect()
+}
@@ -0,0 +1,181 @@
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, BigInt, VarChar};
+
+use crate::consumer::models::assets::AssetOverride;
+use crate::db::PgAsyncPool;
+use crate::error::Error as AppError;
+use crate::schema::*;
+
+const MAX_UID: i64 = std::i64::MAX - 1;
+
+/// Operations that derive/aggregate from the raw on-chain tables rather
+/// than ingest them. Kept on its own pool and connection type so a slow
+/// analytical update can no longer stall block ingestion on
+/// `PgRepo::transaction`.
+#[async_trait]
+pub trait OffChainRepoOperations {
+    fn close_assets_superseded_by(&self, updates: &Vec<AssetOverride>) -> Result<()>;
+    fn reopen_assets_superseded_by(&self, current_superseded_by: &Vec<i64>) -> Result<()>;
+    fn update_assets_block_references(&self, block_uid: &i64) -> Result<()>;
+
+    /// Highest on-chain uid the off-chain worker has fully caught up to.
+    fn get_watermark(&self) -> Result<i64>;
+
+    /// Advances the watermark after the worker processes up to `uid`.
+    fn set_watermark(&self, uid: i64) -> Result<()>;
+
+    /// Rebuilds the `invoke_script_calls` index purely by reading the raw
+    /// `txs_16`/`txs_18` tables, so it can be dropped and recomputed from
+    /// scratch at any time without touching or locking on-chain ingestion.
+    fn rebuild_invoke_call_index(&self) -> Result<()>;
+}
+
+#[derive(Clone)]
+pub struct OffChainRepo {
+    pool: PgAsyncPool,
+}
+
+pub fn new(pool: PgAsyncPool) -> OffChainRepo {
+    OffChainRepo { pool }
+}
+
+pub struct OffChainRepoConnection<'c> {
+    conn: &'c PgConnection,
+}
+
+impl OffChainRepo {
+    pub async fn transaction<F, R>(&self, f: F) -> Result<R>
+    where
+        F: for<'conn> FnOnce(&OffChainRepoConnection<'conn>) -> Result<R>,
+        F: Send + 'static,
+        R: Send + 'static,
+    {
+        let connection = self.pool.get().await?;
+        connection
+            .interact(|conn| {
+                let ops = OffChainRepoConnection { conn };
+                ops.conn.transaction(|| f(&ops))
+            })
+            .await
+            .expect("deadpool interaction failed")
+    }
+}
+
+#[async_trait]
+impl OffChainRepoOperations for OffChainRepoConnection<'_> {
+    fn close_assets_superseded_by(&self, updates: &Vec<AssetOverride>) -> Result<()> {
+        let mut ids = vec![];
+        let mut superseded_by_uids = vec![];
+
+        updates.iter().for_each(|u| {
+            ids.push(&u.id);
+            superseded_by_uids.push(&u.superseded_by);
+        });
+
+        let q = diesel::sql_query(
+            "UPDATE asset_updates
+            SET superseded_by = updates.superseded_by
+            FROM (SELECT UNNEST($1::text[]) as id, UNNEST($2::int8[]) as superseded_by) AS updates
+            WHERE asset_updates.asset_id = updates.id AND asset_updates.superseded_by = $3;",
+        )
+        .bind::<Array<VarChar>, _>(ids)
+        .bind::<Array<BigInt>, _>(superseded_by_uids)
+        .bind::<BigInt, _>(MAX_UID);
+
+        q.execute(self.conn).map(|_| ()).map_err(|err| {
+            let context = format!("Cannot close assets superseded_by: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+    }
+
+    fn reopen_assets_superseded_by(&self, current_superseded_by: &Vec<i64>) -> Result<()> {
+        diesel::sql_query(
+            "UPDATE asset_updates
+            SET superseded_by = $1
+            FROM (SELECT UNNEST($2) AS superseded_by) AS current
+            WHERE asset_updates.superseded_by = current.superseded_by;",
+        )
+        .bind::<BigInt, _>(MAX_UID)
+        .bind::<Array<BigInt>, _>(current_superseded_by)
+        .execute(self.conn)
+        .map(|_| ())
+        .map_err(|err| {
+            let context = format!("Cannot reopen assets superseded_by: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+    }
+
+    fn update_assets_block_references(&self, block_uid: &i64) -> Result<()> {
+        diesel::update(asset_updates::table)
+            .set((asset_updates::block_uid.eq(block_uid),))
+            .filter(asset_updates::block_uid.gt(block_uid))
+            .execute(self.conn)
+            .map(|_| ())
+            .map_err(|err| {
+                let context = format!("Cannot update assets block references: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })
+    }
+
+    fn get_watermark(&self) -> Result<i64> {
+        off_chain_watermark::table
+            .select(off_chain_watermark::uid)
+            .first(self.conn)
+            .optional()
+            .map_err(|err| {
+                let context = format!("Cannot get off-chain watermark: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })
+            // No row yet means the off-chain worker has never run against
+            // this database: start from the genesis watermark instead of
+            // hard-failing on first run.
+            .map(|watermark| watermark.unwrap_or(0))
+    }
+
+    fn set_watermark(&self, uid: i64) -> Result<()> {
+        let updated = diesel::update(off_chain_watermark::table)
+            .set(off_chain_watermark::uid.eq(uid))
+            .execute(self.conn)
+            .map_err(|err| {
+                let context = format!("Cannot set off-chain watermark: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })?;
+
+        // First run: there is no row to update yet (see `get_watermark`).
+        if updated == 0 {
+            diesel::insert_into(off_chain_watermark::table)
+                .values(off_chain_watermark::uid.eq(uid))
+                .execute(self.conn)
+                .map_err(|err| {
+                    let context = format!("Cannot set off-chain watermark: {}", err);
+                    Error::new(AppError::DbDieselError(err)).context(context)
+                })?;
+        }
+        Ok(())
+    }
+
+    fn rebuild_invoke_call_index(&self) -> Result<()> {
+        diesel::sql_query("TRUNCATE invoke_script_calls")
+            .execute(self.conn)
+            .map_err(|err| {
+                let context = format!("Cannot truncate invoke_script_calls: {}", err);
+                Error::new(AppError::DbDieselError(err)).context(context)
+            })?;
+
+        diesel::sql_query(
+            "INSERT INTO invoke_script_calls (tx_uid, dapp_address, function_name, height)
+            SELECT uid, dapp_address, function_name, height FROM txs_16
+            UNION ALL
+            SELECT uid, dapp_address, function_name, height FROM txs_18",
+        )
+        .execute(self.conn)
+        .map(|_| ())
+        .map_err(|err| {
+            let context = format!("Cannot rebuild invoke_script_calls: {}", err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+    }
+}
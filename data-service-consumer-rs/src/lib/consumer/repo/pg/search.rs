@@ -0,0 +1,83 @@
+use anyhow::{Error, Result};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{Double, Int8, Nullable, Text};
+
+use crate::error::Error as AppError;
+
+/// How `search_assets` should rank candidates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Lexeme/prefix matching only, via the existing `TsVector` index.
+    FullText,
+    /// `pg_trgm` similarity only; tolerates misspellings and fragments.
+    Trigram,
+    /// A weighted blend of both, so exact token hits outrank fuzzy ones
+    /// but a misspelling like "bitcon" still finds "Bitcoin".
+    Hybrid,
+}
+
+#[derive(Clone, Debug, QueryableByName)]
+pub struct AssetSearchHit {
+    #[sql_type = "Text"]
+    pub asset_id: String,
+    #[sql_type = "Nullable<Text>"]
+    pub asset_name: Option<String>,
+    #[sql_type = "Nullable<Text>"]
+    pub ticker: Option<String>,
+    #[sql_type = "Double"]
+    pub score: f64,
+}
+
+/// Searches `assets_metadata`/`assets_names_map` by `query`, ranked
+/// according to `mode`. `Hybrid` blends normalized full-text `ts_rank`
+/// with trigram `similarity()` so exact token matches still win but
+/// near-misses surface too.
+pub fn search_assets(
+    conn: &PgConnection,
+    query: &str,
+    mode: SearchMode,
+    limit: i64,
+) -> Result<Vec<AssetSearchHit>> {
+    let sql = match mode {
+        SearchMode::FullText => {
+            "SELECT n.asset_id, n.asset_name, m.ticker,
+                    ts_rank(n.searchable_asset_name, plainto_tsquery($1))::float8 AS score
+             FROM assets_names_map n
+             LEFT JOIN assets_metadata m ON m.asset_id = n.asset_id
+             WHERE n.searchable_asset_name @@ plainto_tsquery($1)
+             ORDER BY score DESC
+             LIMIT $2"
+        }
+        SearchMode::Trigram => {
+            "SELECT n.asset_id, n.asset_name, m.ticker,
+                    GREATEST(similarity(n.asset_name, $1), similarity(m.ticker, $1))::float8 AS score
+             FROM assets_names_map n
+             LEFT JOIN assets_metadata m ON m.asset_id = n.asset_id
+             WHERE n.asset_name % $1 OR m.ticker % $1
+             ORDER BY score DESC
+             LIMIT $2"
+        }
+        SearchMode::Hybrid => {
+            "SELECT n.asset_id, n.asset_name, m.ticker,
+                    (0.7 * ts_rank(n.searchable_asset_name, plainto_tsquery($1))
+                     + 0.3 * GREATEST(similarity(n.asset_name, $1), similarity(m.ticker, $1)))::float8 AS score
+             FROM assets_names_map n
+             LEFT JOIN assets_metadata m ON m.asset_id = n.asset_id
+             WHERE n.searchable_asset_name @@ plainto_tsquery($1)
+                OR n.asset_name % $1
+                OR m.ticker % $1
+             ORDER BY score DESC
+             LIMIT $2"
+        }
+    };
+
+    diesel::sql_query(sql)
+        .bind::<Text, _>(query)
+        .bind::<Int8, _>(limit)
+        .get_results(conn)
+        .map_err(|err| {
+            let context = format!("Cannot search assets for {:?}: {}", query, err);
+            Error::new(AppError::DbDieselError(err)).context(context)
+        })
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::consumer::models::assets::{AssetOrigin, AssetOverride, AssetUpdate, DeletedAsset};
+
+/// An asset lifecycle event, emitted at the same commit boundary as the
+/// Postgres write that produced it so downstream consumers never
+/// diverge from what's actually persisted.
+#[derive(Clone, Debug, Serialize)]
+pub enum AssetEvent {
+    AssetIssued(AssetOrigin, AssetUpdate),
+    AssetUpdated(AssetUpdate),
+    AssetSuperseded(AssetOverride),
+    AssetRolledBack(DeletedAsset),
+}
+
+/// One event plus the bookkeeping a sink needs to replay or resume:
+/// the block it was committed with and a cursor that only ever
+/// increases, so a sink can store "last cursor delivered" and resume
+/// from there after a restart.
+#[derive(Clone, Debug, Serialize)]
+pub struct SinkEnvelope {
+    pub cursor: u64,
+    pub block_uid: i64,
+    pub event: AssetEvent,
+}
+
+/// A destination for the asset event stream. Implementors must be
+/// at-least-once: `send` may be called again with an envelope already
+/// delivered (e.g. after a crash before the cursor was persisted), so
+/// sinks should be idempotent on `cursor`.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn send(&self, envelope: &SinkEnvelope) -> Result<()>;
+
+    /// The last cursor this sink durably recorded as delivered, so a
+    /// fan-out can resume each sink independently after a restart.
+    async fn cursor(&self) -> Result<Option<u64>>;
+}
+
+/// Keeps the current Postgres-only behavior available as a `Sink`, so
+/// existing consumers of this crate see no change by default.
+pub struct PostgresSink;
+
+#[async_trait]
+impl Sink for PostgresSink {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn send(&self, _envelope: &SinkEnvelope) -> Result<()> {
+        // The Postgres write already happened in the same transaction
+        // that produced this event; nothing further to deliver.
+        Ok(())
+    }
+
+    async fn cursor(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+/// Where a sink's last-delivered cursor is durably recorded, so
+/// `FanOut::dispatch` can resume each sink from where it left off after
+/// a restart instead of re-sending the whole stream. Injected (like
+/// `MessageQueueSink`'s `publish`) so this module isn't tied to one
+/// storage backend; a real deployment would back this with a small
+/// Postgres table keyed by sink name, the same way `off_chain_watermark`
+/// tracks the off-chain worker's progress.
+#[async_trait]
+pub trait CursorStore: Send + Sync {
+    async fn load(&self, sink_name: &str) -> Result<Option<u64>>;
+    async fn store(&self, sink_name: &str, cursor: u64) -> Result<()>;
+}
+
+/// In-process `CursorStore`: survives for the lifetime of this
+/// `MessageQueueSink`, but not a process restart. Good enough for tests
+/// and for callers that don't need cross-restart resume.
+#[derive(Default)]
+pub struct InMemoryCursorStore {
+    cursors: Mutex<HashMap<String, u64>>,
+}
+
+#[async_trait]
+impl CursorStore for InMemoryCursorStore {
+    async fn load(&self, sink_name: &str) -> Result<Option<u64>> {
+        Ok(self
+            .cursors
+            .lock()
+            .expect("cursor store mutex poisoned")
+            .get(sink_name)
+            .copied())
+    }
+
+    async fn store(&self, sink_name: &str, cursor: u64) -> Result<()> {
+        self.cursors
+            .lock()
+            .expect("cursor store mutex poisoned")
+            .insert(sink_name.to_string(), cursor);
+        Ok(())
+    }
+}
+
+/// Publishes each event as JSON to an external message queue (Kafka,
+/// NATS, ...). `publish` is injected so this crate doesn't have to pick
+/// one client library for every deployment; `cursor_store` is injected
+/// the same way so callers can back it with Postgres instead of the
+/// in-process default.
+pub struct MessageQueueSink<P> {
+    name: String,
+    publish: P,
+    cursor_store: Arc<dyn CursorStore>,
+}
+
+impl<P> MessageQueueSink<P>
+where
+    P: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, publish: P) -> Self {
+        Self::with_cursor_store(name, publish, Arc::new(InMemoryCursorStore::default()))
+    }
+
+    pub fn with_cursor_store(
+        name: impl Into<String>,
+        publish: P,
+        cursor_store: Arc<dyn CursorStore>,
+    ) -> Self {
+        MessageQueueSink {
+            name: name.into(),
+            publish,
+            cursor_store,
+        }
+    }
+}
+
+#[async_trait]
+impl<P> Sink for MessageQueueSink<P>
+where
+    P: Fn(&str, Vec<u8>) -> Result<()> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send(&self, envelope: &SinkEnvelope) -> Result<()> {
+        let payload = serde_json::to_vec(envelope)?;
+        (self.publish)(&self.name, payload)?;
+        self.cursor_store.store(&self.name, envelope.cursor).await
+    }
+
+    async fn cursor(&self) -> Result<Option<u64>> {
+        self.cursor_store.load(&self.name).await
+    }
+}
+
+/// Delivers each event to an ordered list of sinks, in order, skipping
+/// any sink whose recorded cursor already reaches the event's cursor.
+/// `dispatch` stops at the first sink that fails, so dispatching the same
+/// envelope again delivers it to the sinks that did not get it. Only the
+/// envelope passed in is sent: earlier events a sink missed are not
+/// replayed.
+pub struct FanOut {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl FanOut {
+    pub fn new(sinks: Vec<Box<dyn Sink>>) -> Self {
+        FanOut { sinks }
+    }
+
+    pub async fn dispatch(&self, envelope: &SinkEnvelope) -> Result<()> {
+        for sink in &self.sinks {
+            if let Some(cursor) = sink.cursor().await? {
+                if cursor >= envelope.cursor {
+                    // Already delivered past this point; skip to stay
+                    // at-least-once rather than re-sending everything.
+                    continue;
+                }
+            }
+            sink.send(envelope).await?;
+        }
+        Ok(())
+    }
+}